@@ -1,23 +1,130 @@
 use clap::{App, Arg};
+use regex::Regex;
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+mod pretty;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
 
+// nlに倣い、本文/ヘッダー/フッターの各行に附番するかどうかを決めるスタイル
+#[derive(Debug, Clone)]
+pub enum NumberStyle {
+    All,             // a: すべての行に附番
+    NonEmpty,        // t: 空行ではない行にのみ附番（旧 -b の挙動）
+    None,            // n: 附番しない
+    Pattern(Regex),  // pREGEX: 正規表現にマッチした行にのみ附番
+}
+
+impl NumberStyle {
+    fn applies(&self, line: &str) -> bool {
+        match self {
+            NumberStyle::All => true,
+            NumberStyle::NonEmpty => !line.is_empty(),
+            NumberStyle::None => false,
+            NumberStyle::Pattern(re) => re.is_match(line),
+        }
+    }
+}
+
+// 文字列で渡されたスタイル指定（a/t/n/pREGEX）をパースする
+fn parse_style(raw: &str) -> MyResult<NumberStyle> {
+    match raw {
+        "a" => Ok(NumberStyle::All),
+        "t" => Ok(NumberStyle::NonEmpty),
+        "n" => Ok(NumberStyle::None),
+        _ if raw.starts_with('p') => Ok(NumberStyle::Pattern(Regex::new(&raw[1..])?)),
+        _ => Err(From::from(format!("illegal numbering style: {}", raw))),
+    }
+}
+
+
+// 行番号の表示形式
+#[derive(Debug, Clone, Copy)]
+pub enum NumberFormat {
+    LeftNoZero,   // ln: 左詰め、ゼロ埋めなし
+    RightNoZero,  // rn: 右詰め、ゼロ埋めなし（従来の挙動）
+    RightZero,    // rz: 右詰め、ゼロ埋めあり
+}
+
+fn parse_number_format(raw: &str) -> MyResult<NumberFormat> {
+    match raw {
+        "ln" => Ok(NumberFormat::LeftNoZero),
+        "rn" => Ok(NumberFormat::RightNoZero),
+        "rz" => Ok(NumberFormat::RightZero),
+        _ => Err(From::from(format!("illegal number format: {}", raw))),
+    }
+}
+
+fn format_number(num: i64, width: usize, format: NumberFormat) -> String {
+    match format {
+        NumberFormat::LeftNoZero => format!("{:<width$}", num, width = width),
+        NumberFormat::RightNoZero => format!("{:>width$}", num, width = width),
+        NumberFormat::RightZero => format!("{:0>width$}", num, width = width),
+    }
+}
+
+
+// 論理ページ上のセクション。区切り行 \:\:\:, \:\:, \: でこの間を切り替える
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Header,
+    Body,
+    Footer,
+}
+
+// 行番号カウンタ。開始値と増分を保持し、-p指定時はファイルごとにリセットされる
+#[derive(Debug, Clone)]
+struct LineCounter {
+    start: i64,
+    increment: i64,
+    current: i64,
+}
+
+impl LineCounter {
+    fn new(start: i64, increment: i64) -> Self {
+        Self { start, increment, current: start }
+    }
+
+    fn reset(&mut self) {
+        self.current = self.start;
+    }
+
+    fn next(&mut self) -> i64 {
+        let value = self.current;
+        self.current += self.increment;
+        value
+    }
+}
+
+
 // コマンドの引数、オプションを格納する構造体
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
     files: Vec<String>,
-    number_lines: bool,
-    number_nonblank_lines: bool,
+    header_style: NumberStyle,
+    body_style: NumberStyle,
+    footer_style: NumberStyle,
+    width: usize,
+    separator: String,
+    starting_line_number: i64,
+    line_increment: i64,
+    number_format: NumberFormat,
+    reset_per_file: bool,
+    show_ends: bool,
+    show_tabs: bool,
+    show_nonprinting: bool,
+    squeeze_blank: bool,
+    color_mode: Option<pretty::ColorMode>,
+    style_components: Vec<pretty::StyleComponent>,
 }
 
 
 // コマンドに与えられた引数、オプションを解析し、Config構造体を返す
-// 実例：catr -n hoge.txt fuga.txt
-// -> Config(files: ["hoge.txt", "fuga.txt"], number_lines: true, number_nonblank_lines: false)
+// 実例：catr -b a hoge.txt fuga.txt
+// -> Config(files: ["hoge.txt", "fuga.txt"], body_style: All, ...)
 pub fn get_args() -> MyResult<Config> {
     let matches = App::new("catr")
         .version("0.1.0")
@@ -31,26 +138,176 @@ pub fn get_args() -> MyResult<Config> {
                 .default_value("-"),
         )
         .arg(
-            Arg::with_name("number_lines")
+            Arg::with_name("body_style")
+                .short("b")
+                .long("body-numbering")
+                .value_name("STYLE")
+                .help("numbering style for body lines: a (all), t (non-blank), n (none), or pREGEX")
+                .takes_value(true)
+                .default_value("n"),
+        )
+        .arg(
+            Arg::with_name("header_style")
+                .short("h")
+                .long("header-numbering")
+                .value_name("STYLE")
+                .help("numbering style for header section lines")
+                .takes_value(true)
+                .default_value("n"),
+        )
+        .arg(
+            Arg::with_name("footer_style")
+                .short("f")
+                .long("footer-numbering")
+                .value_name("STYLE")
+                .help("numbering style for footer section lines")
+                .takes_value(true)
+                .default_value("n"),
+        )
+        .arg(
+            Arg::with_name("width")
+                .short("w")
+                .long("width")
+                .value_name("WIDTH")
+                .help("number column width")
+                .takes_value(true)
+                .default_value("6"),
+        )
+        // catの-sは空行の圧縮に割り当てているため、短縮オプションは付与しない
+        .arg(
+            Arg::with_name("separator")
+                .long("separator")
+                .value_name("STRING")
+                .help("text inserted between the line number and the line")
+                .takes_value(true)
+                .default_value("\t"),
+        )
+        // catの-vは非表示文字の可視化に割り当てているため、短縮オプションは付与しない
+        .arg(
+            Arg::with_name("starting_line_number")
+                .long("starting-line-number")
+                .value_name("NUMBER")
+                .help("first line number")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("line_increment")
+                .short("i")
+                .long("line-increment")
+                .value_name("NUMBER")
+                .help("line number increment")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("number_format")
                 .short("n")
-                .long("number")
-                .help("number all output lines")
-                .takes_value(false)
-                .conflicts_with("number_nonblank_lines") // number_linesとnumber_nonblank_linesの同時指定は不可とする
+                .long("number-format")
+                .value_name("FORMAT")
+                .help("line number format: ln, rn, or rz")
+                .takes_value(true)
+                .possible_values(&["ln", "rn", "rz"])
+                .default_value("rn"),
         )
         .arg(
-            Arg::with_name("number_nonblank_lines")
-                .short("b")
-                .long("number-nonblank")
-                .help("number nonempty output lines")
-                .takes_value(false)
+            Arg::with_name("reset_per_file")
+                .short("p")
+                .long("reset-per-file")
+                .help("restart numbering from the starting line number for each file")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("show_ends")
+                .short("E")
+                .long("show-ends")
+                .help("display $ at end of each line")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("show_tabs")
+                .short("T")
+                .long("show-tabs")
+                .help("display TAB characters as ^I")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("show_nonprinting")
+                .short("v")
+                .long("show-nonprinting")
+                .help("use ^ and M- notation, except for LFD and TAB")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("show_all")
+                .short("A")
+                .long("show-all")
+                .help("equivalent to -ET --show-nonprinting")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("squeeze_blank")
+                .short("s")
+                .long("squeeze-blank")
+                .help("suppress repeated empty output lines")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .value_name("WHEN")
+                .help("turn on a bat-like syntax highlighted pretty printer: auto, always, or never")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"]),
+        )
+        .arg(
+            Arg::with_name("style")
+                .long("style")
+                .value_name("COMPONENTS")
+                .help("comma separated pretty-print components: header, grid, numbers")
+                .takes_value(true)
+                .default_value("header,grid,numbers"),
         )
         .get_matches();
 
+    let width = matches
+        .value_of("width")
+        .unwrap()
+        .parse()
+        .map_err(|_| "illegal width value")?;
+    let starting_line_number = matches
+        .value_of("starting_line_number")
+        .unwrap()
+        .parse()
+        .map_err(|_| "illegal starting line number")?;
+    let line_increment = matches
+        .value_of("line_increment")
+        .unwrap()
+        .parse()
+        .map_err(|_| "illegal line increment")?;
+
+    let show_all = matches.is_present("show_all");
+
     Ok(Config {
         files: matches.values_of_lossy("files").unwrap(),
-        number_lines: matches.is_present("number_lines"),
-        number_nonblank_lines: matches.is_present("number_nonblank_lines")
+        header_style: parse_style(matches.value_of("header_style").unwrap())?,
+        body_style: parse_style(matches.value_of("body_style").unwrap())?,
+        footer_style: parse_style(matches.value_of("footer_style").unwrap())?,
+        width,
+        separator: matches.value_of("separator").unwrap().to_string(),
+        starting_line_number,
+        line_increment,
+        number_format: parse_number_format(matches.value_of("number_format").unwrap())?,
+        reset_per_file: matches.is_present("reset_per_file"),
+        show_ends: show_all || matches.is_present("show_ends"),
+        show_tabs: show_all || matches.is_present("show_tabs"),
+        show_nonprinting: show_all || matches.is_present("show_nonprinting"),
+        squeeze_blank: matches.is_present("squeeze_blank"),
+        color_mode: matches
+            .value_of("color")
+            .map(pretty::parse_color_mode)
+            .transpose()?,
+        style_components: pretty::parse_style_components(matches.value_of("style").unwrap())?,
     })
 }
 
@@ -64,43 +321,317 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
+// セクション区切り行（\:\:\:, \:\:, \:）であれば、切り替わる先のセクションを返す
+fn section_delimiter(line: &str) -> Option<Section> {
+    match line {
+        "\\:\\:\\:" => Some(Section::Header),
+        "\\:\\:" => Some(Section::Body),
+        "\\:" => Some(Section::Footer),
+        _ => None,
+    }
+}
+
+fn style_for(config: &Config, section: Section) -> &NumberStyle {
+    match section {
+        Section::Header => &config.header_style,
+        Section::Body => &config.body_style,
+        Section::Footer => &config.footer_style,
+    }
+}
 
-// メインの処理（ファイルの各行/行番号の出力）を実行する
+// -v/--show-nonprinting: 制御文字・高位ビットのバイトを^X/M-X記法に変換する
+// -T/--show-tabs: タブ文字を^Iとして表示する
+// バイト単位で組み立てるため、非UTF-8入力でもfrom_utf8_lossyによる欠落が起きない
+fn visualize(bytes: &[u8], config: &Config) -> Vec<u8> {
+    if config.show_nonprinting {
+        let mut out = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            match b {
+                b'\t' if !config.show_tabs => out.push(b'\t'),
+                b'\t' => out.extend_from_slice(b"^I"),
+                0..=8 | 10..=31 => {
+                    out.push(b'^');
+                    out.push(b + 64);
+                }
+                127 => out.extend_from_slice(b"^?"),
+                255 => out.extend_from_slice(b"M-^?"),
+                128..=159 => {
+                    out.extend_from_slice(b"M-^");
+                    out.push(b - 128 + 64);
+                }
+                160..=254 => {
+                    out.extend_from_slice(b"M-");
+                    out.push(b - 128);
+                }
+                _ => out.push(b),
+            }
+        }
+        out
+    } else if config.show_tabs {
+        let mut out = Vec::with_capacity(bytes.len());
+        for &b in bytes {
+            if b == b'\t' {
+                out.extend_from_slice(b"^I");
+            } else {
+                out.push(b);
+            }
+        }
+        out
+    } else {
+        bytes.to_vec()
+    }
+}
+
+
+// --colorが指定されていればシンタックスハイライト付きのprettyモードへ、
+// そうでなければ従来どおりプレーンなcatとして出力する
 pub fn run(config: Config) -> MyResult<()> {
-    for filename in config.files {
-        match open(&filename) {
+    match config.color_mode {
+        Some(mode) => pretty::run(&config, mode),
+        None => run_plain(config),
+    }
+}
+
+// プレーンなcatのパスを標準出力にBufWriterで書き出す薄いラッパー
+pub(crate) fn run_plain(config: Config) -> MyResult<()> {
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    cat(&config, &mut out)
+}
+
+// ライブラリとして再利用できるよう、出力先をimpl Writeとして受け取るコア処理。
+// 1行ごとにprintln!でロック/フラッシュする代わりに、呼び出し元が用意したwriterへまとめて書き込む
+pub fn cat(config: &Config, out: &mut impl Write) -> MyResult<()> {
+    let mut counter = LineCounter::new(config.starting_line_number, config.line_increment);
+    let mut prev_blank = false;
+
+    for filename in &config.files {
+        match open(filename) {
             Err(err) => eprintln!("Failed to open {}: {}", filename, err),
 
-            Ok(file) => {
-                // number_nonblank_linesオプションで表示する行番号を保持する
-                let mut current_nonblank_line_num = 0;
+            Ok(mut file) => {
+                if config.reset_per_file {
+                    counter.reset();
+                }
+
+                let mut section = Section::Body;
+                let mut buf: Vec<u8> = Vec::new();
+
+                loop {
+                    buf.clear();
+                    // -vが非UTF-8バイトも扱えるよう、lines()ではなく生バイトで読み込む
+                    let bytes_read = file.read_until(b'\n', &mut buf)?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    let had_newline = buf.last() == Some(&b'\n');
+                    if had_newline {
+                        buf.pop();
+                    }
+
+                    let raw_text = String::from_utf8_lossy(&buf);
 
-                for (line_index, line) in file.lines().enumerate() {
-                    let line = line?;
+                    if let Some(next_section) = section_delimiter(&raw_text) {
+                        section = next_section;
+                        continue;
+                    }
 
-                    // 与えられたオプションによって処理を分岐する
-                    // 行番号を表示 *空行も含め附番*
-                    if config.number_lines {
-                        println!("{:>6}\t{}", line_index + 1, line);
+                    // -s/--squeeze-blank: 連続する空行は1行に圧縮し、行番号も消費しない
+                    let is_blank = buf.is_empty();
+                    if config.squeeze_blank && is_blank && prev_blank {
                         continue;
                     }
+                    prev_blank = is_blank;
 
-                    // number_nonblank_lines => *空行ではない行に附番*
-                    if config.number_nonblank_lines {
-                        if !line.is_empty() {
-                            current_nonblank_line_num += 1;
-                            println!("{:>6}\t{}", current_nonblank_line_num, line);
-                        } else {
-                            println!();
+                    // 附番スタイルがNoneのセクションでは附番そのものが無効なので、
+                    // 桁揃え用のパディングも含めて何も前置しない（素のcatと同じ出力にする）
+                    let style = style_for(config, section);
+                    let prefix = match style {
+                        NumberStyle::None => String::new(),
+                        _ if style.applies(&raw_text) => {
+                            let number = format_number(counter.next(), config.width, config.number_format);
+                            format!("{}{}", number, config.separator)
                         }
-                        continue;
+                        _ => format!("{:width$}{}", "", config.separator, width = config.width),
+                    };
+
+                    // いずれの表示変換フラグも無ければバイト列をそのまま書き出し、非UTF-8入力を
+                    // from_utf8_lossyで破壊しない（プレーンなcatとしてのバイト忠実性を保つ）
+                    write!(out, "{}", prefix)?;
+                    if config.show_nonprinting || config.show_tabs || config.show_ends {
+                        let mut display = visualize(&buf, config);
+                        if config.show_ends {
+                            display.push(b'$');
+                        }
+                        out.write_all(&display)?;
+                    } else {
+                        out.write_all(&buf)?;
                     }
 
-                    // オプションが無し => 行番号は表示せず、行の文字列をそのまま表示
-                    println!("{}", line);
+                    // 末尾に改行が無い入力は、出力でも改行を付け足さずに忠実に再現する
+                    if had_newline {
+                        out.write_all(b"\n")?;
+                    }
                 }
             }
         }
     }
     Ok(())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(files: Vec<&str>) -> Config {
+        Config {
+            files: files.into_iter().map(String::from).collect(),
+            header_style: NumberStyle::None,
+            body_style: NumberStyle::None,
+            footer_style: NumberStyle::None,
+            width: 6,
+            separator: "\t".to_string(),
+            starting_line_number: 1,
+            line_increment: 1,
+            number_format: NumberFormat::RightNoZero,
+            reset_per_file: false,
+            show_ends: false,
+            show_tabs: false,
+            show_nonprinting: false,
+            squeeze_blank: false,
+            color_mode: None,
+            style_components: Vec::new(),
+        }
+    }
+
+    fn write_temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("catr_test_{}", name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_body_numbering_styles() {
+        assert!(matches!(parse_style("a").unwrap(), NumberStyle::All));
+        assert!(matches!(parse_style("t").unwrap(), NumberStyle::NonEmpty));
+        assert!(matches!(parse_style("n").unwrap(), NumberStyle::None));
+        assert!(matches!(parse_style("p^foo").unwrap(), NumberStyle::Pattern(_)));
+        assert!(parse_style("bogus").is_err());
+    }
+
+    #[test]
+    fn number_style_applies_matches_style() {
+        assert!(NumberStyle::All.applies("anything"));
+        assert!(NumberStyle::All.applies(""));
+        assert!(NumberStyle::NonEmpty.applies("hi"));
+        assert!(!NumberStyle::NonEmpty.applies(""));
+        assert!(!NumberStyle::None.applies("hi"));
+        let pattern = parse_style("p^foo").unwrap();
+        assert!(pattern.applies("foobar"));
+        assert!(!pattern.applies("barfoo"));
+    }
+
+    #[test]
+    fn formats_numbers_per_format() {
+        assert_eq!(format_number(3, 6, NumberFormat::LeftNoZero), "3     ");
+        assert_eq!(format_number(3, 6, NumberFormat::RightNoZero), "     3");
+        assert_eq!(format_number(3, 6, NumberFormat::RightZero), "000003");
+    }
+
+    #[test]
+    fn line_counter_honours_start_increment_and_reset() {
+        let mut counter = LineCounter::new(5, 2);
+        assert_eq!(counter.next(), 5);
+        assert_eq!(counter.next(), 7);
+        counter.reset();
+        assert_eq!(counter.next(), 5);
+    }
+
+    #[test]
+    fn cat_numbers_every_line_with_style_all() {
+        let path = write_temp_file("numbers_all", b"one\ntwo\n");
+        let mut config = test_config(vec![&path]);
+        config.body_style = NumberStyle::All;
+        let mut out = Vec::new();
+        cat(&config, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "     1\tone\n     2\ttwo\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn visualize_show_nonprinting_uses_caret_and_meta_notation() {
+        let mut config = test_config(vec!["-"]);
+        config.show_nonprinting = true;
+        assert_eq!(visualize(b"\x01\x7f\xff", &config), b"^A^?M-^?");
+    }
+
+    #[test]
+    fn visualize_show_tabs_renders_caret_i_without_touching_other_bytes() {
+        let mut config = test_config(vec!["-"]);
+        config.show_tabs = true;
+        assert_eq!(visualize(b"a\tb", &config), b"a^Ib");
+    }
+
+    #[test]
+    fn cat_appends_dollar_for_show_ends() {
+        let path = write_temp_file("show_ends", b"one\ntwo");
+        let mut config = test_config(vec![&path]);
+        config.show_ends = true;
+        let mut out = Vec::new();
+        cat(&config, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "one$\ntwo$");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cat_squeezes_repeated_blank_lines_without_consuming_numbers() {
+        let path = write_temp_file("squeeze_blank", b"a\n\n\n\nb\n");
+        let mut config = test_config(vec![&path]);
+        config.squeeze_blank = true;
+        config.body_style = NumberStyle::All;
+        let mut out = Vec::new();
+        cat(&config, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "     1\ta\n     2\t\n     3\tb\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cat_preserves_missing_final_newline() {
+        let path = write_temp_file("no_trailing_newline", b"no newline here");
+        let config = test_config(vec![&path]);
+        let mut out = Vec::new();
+        cat(&config, &mut out).unwrap();
+        assert_eq!(out, b"no newline here");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cat_passes_raw_bytes_through_when_no_flags_set() {
+        let bytes = b"caf\xe9\n".to_vec();
+        let path = write_temp_file("raw_bytes", &bytes);
+        let config = test_config(vec![&path]);
+        let mut out = Vec::new();
+        cat(&config, &mut out).unwrap();
+        assert_eq!(out, bytes);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn cat_writes_through_an_arbitrary_writer_across_multiple_files() {
+        let first = write_temp_file("multi_a", b"hello\n");
+        let second = write_temp_file("multi_b", b"world\n");
+        let config = test_config(vec![&first, &second]);
+        let mut out: Vec<u8> = Vec::new();
+        cat(&config, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "hello\nworld\n");
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+// batのPrettyPrinterを参考にした、シンタックスハイライト付き出力モード
+// --color=neverの場合はrun()と完全に同じ出力になるよう、そちらにそのまま委譲する
+
+use crate::{run_plain, Config, LineCounter, MyResult};
+use std::io::{BufWriter, Read, Write};
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::as_24_bit_terminal_escaped;
+
+// --color[=auto|always|never] の指定値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+pub fn parse_color_mode(raw: &str) -> MyResult<ColorMode> {
+    match raw {
+        "auto" => Ok(ColorMode::Auto),
+        "always" => Ok(ColorMode::Always),
+        "never" => Ok(ColorMode::Never),
+        _ => Err(From::from(format!("illegal color mode: {}", raw))),
+    }
+}
+
+// --styleで選択できる表示コンポーネント
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StyleComponent {
+    Header, // ファイル名を表示する見出し行
+    Grid,   // 行番号と本文の間の区切り罫線
+    Numbers, // 行番号（既存の附番エンジンを再利用する）
+}
+
+pub fn parse_style_components(raw: &str) -> MyResult<Vec<StyleComponent>> {
+    raw.split(',')
+        .map(|s| match s {
+            "header" => Ok(StyleComponent::Header),
+            "grid" => Ok(StyleComponent::Grid),
+            "numbers" => Ok(StyleComponent::Numbers),
+            _ => Err(From::from(format!("illegal style component: {}", s))),
+        })
+        .collect()
+}
+
+fn is_enabled(mode: ColorMode) -> bool {
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => atty::is(atty::Stream::Stdout),
+    }
+}
+
+// 標準出力がターミナルでなければ80桁にフォールバックする
+fn terminal_width() -> usize {
+    terminal_size::terminal_size()
+        .map(|(terminal_size::Width(w), _)| w as usize)
+        .unwrap_or(80)
+}
+
+// ファイル拡張子から構文定義を選んでシンタックスハイライトを行うプリティプリンタ
+pub struct PrettyPrinter {
+    syntax_set: SyntaxSet,
+    theme_set: ThemeSet,
+    components: Vec<StyleComponent>,
+    width: usize,
+}
+
+impl PrettyPrinter {
+    fn new(components: Vec<StyleComponent>) -> Self {
+        Self {
+            syntax_set: SyntaxSet::load_defaults_newlines(),
+            theme_set: ThemeSet::load_defaults(),
+            components,
+            width: terminal_width(),
+        }
+    }
+
+    fn print_file(
+        &self,
+        filename: &str,
+        config: &Config,
+        counter: &mut LineCounter,
+        out: &mut impl Write,
+    ) -> MyResult<()> {
+        // "-"は標準入力として扱う。プレーンなcatのopen()と同じ規約に揃える
+        let mut content = String::new();
+        crate::open(filename)?.read_to_string(&mut content)?;
+        let syntax = std::path::Path::new(filename)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.syntax_set.find_syntax_by_extension(ext))
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
+        let theme = &self.theme_set.themes["base16-ocean.dark"];
+        let mut highlighter = HighlightLines::new(syntax, theme);
+
+        if self.components.contains(&StyleComponent::Header) {
+            writeln!(out, "{:─<width$}", format!("── {} ", filename), width = self.width)?;
+        }
+
+        for line in content.lines() {
+            let ranges = highlighter.highlight_line(line, &self.syntax_set)?;
+            let escaped = as_24_bit_terminal_escaped(&ranges[..], false);
+
+            // 附番エンジンを再利用し、--width/--starting-line-number/-i/--number-format/本文スタイルに従う
+            if self.components.contains(&StyleComponent::Numbers) {
+                if config.body_style.applies(line) {
+                    let number = crate::format_number(counter.next(), config.width, config.number_format);
+                    write!(out, "{}{}", number, config.separator)?;
+                } else {
+                    write!(out, "{:width$}{}", "", config.separator, width = config.width)?;
+                }
+            }
+            if self.components.contains(&StyleComponent::Grid) {
+                write!(out, "│ ")?;
+            }
+            writeln!(out, "{}", escaped)?;
+        }
+        Ok(())
+    }
+}
+
+// config.color_modeがSomeの場合の分岐先。colorが実際には無効(neverまたは非tty)なら
+// プレーンなrun_plainへそのまま委譲し、スクリプトからの見え方を変えない
+pub fn run(config: &Config, mode: ColorMode) -> MyResult<()> {
+    if !is_enabled(mode) {
+        let mut plain_config = config.clone();
+        plain_config.color_mode = None;
+        return run_plain(plain_config);
+    }
+
+    let printer = PrettyPrinter::new(config.style_components.clone());
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut counter = LineCounter::new(config.starting_line_number, config.line_increment);
+    for filename in &config.files {
+        if config.reset_per_file {
+            counter.reset();
+        }
+        if let Err(err) = printer.print_file(filename, config, &mut counter, &mut out) {
+            eprintln!("Failed to open {}: {}", filename, err);
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_color_mode_values() {
+        assert_eq!(parse_color_mode("auto").unwrap(), ColorMode::Auto);
+        assert_eq!(parse_color_mode("always").unwrap(), ColorMode::Always);
+        assert_eq!(parse_color_mode("never").unwrap(), ColorMode::Never);
+        assert!(parse_color_mode("bogus").is_err());
+    }
+
+    #[test]
+    fn parses_style_components() {
+        let components = parse_style_components("header,grid,numbers").unwrap();
+        assert_eq!(
+            components,
+            vec![StyleComponent::Header, StyleComponent::Grid, StyleComponent::Numbers]
+        );
+        assert!(parse_style_components("bogus").is_err());
+    }
+
+    #[test]
+    fn is_enabled_honours_always_and_never_without_checking_the_terminal() {
+        assert!(is_enabled(ColorMode::Always));
+        assert!(!is_enabled(ColorMode::Never));
+    }
+}